@@ -0,0 +1,68 @@
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Literal),
+    Grouping(Box<Grouping>),
+    Unary(Box<UnaryExpr>),
+    Binary(Box<BinaryExpr>),
+    Variable(String),
+    Assign(Box<Assign>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assign {
+    pub name: String,
+    pub value: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grouping {
+    pub expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnaryExpr {
+    pub operator: Operator,
+    pub right: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryExpr {
+    pub left: Expr,
+    pub operator: Operator,
+    pub right: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Bang,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    NumberLiteral(f64),
+    StringLiteral(String),
+    BoolLiteral(bool),
+    NilLiteral,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Expr(Expr),
+    Print(Expr),
+    Var(String, Option<Expr>),
+    Block(Vec<Stmt>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    While(Expr, Box<Stmt>),
+}
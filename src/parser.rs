@@ -1,110 +1,367 @@
 use ast::*;
 use scanner::{Token, TokenWithContext};
+use std::fmt;
 use std::iter::Peekable;
 
-pub fn parse(tokens: Vec<TokenWithContext>) -> Expr {
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    UnexpectedToken(TokenWithContext, String),
+    ExpectedExpression(TokenWithContext),
+    MissingRightParen(TokenWithContext),
+    TrailingTokens(TokenWithContext),
+    UnexpectedEof,
+}
+
+impl ParseError {
+    fn offending_token(&self) -> Option<&TokenWithContext> {
+        match *self {
+            ParseError::UnexpectedToken(ref token, _) => Some(token),
+            ParseError::ExpectedExpression(ref token) => Some(token),
+            ParseError::MissingRightParen(ref token) => Some(token),
+            ParseError::TrailingTokens(ref token) => Some(token),
+            ParseError::UnexpectedEof => None,
+        }
+    }
+
+    fn message(&self) -> &str {
+        match *self {
+            ParseError::UnexpectedToken(_, ref message) => message,
+            ParseError::ExpectedExpression(_) => "Expected expression.",
+            ParseError::MissingRightParen(_) => "Expected ')' after expression.",
+            ParseError::TrailingTokens(_) => "Unexpected trailing tokens after expression.",
+            ParseError::UnexpectedEof => "Unexpected end of input.",
+        }
+    }
+}
+
+// Renders a diagnostic in the `[line N] Error at '<lexeme>': <message>`
+// style, using whatever source position the offending token carried.
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.offending_token() {
+            Some(token) => {
+                write!(f,
+                       "[line {}] Error at '{}': {}",
+                       token.context.line,
+                       token.context.lexeme,
+                       self.message())
+            }
+            None => write!(f, "Error at end: {}", self.message()),
+        }
+    }
+}
+
+pub fn parse(tokens: Vec<TokenWithContext>) -> Result<Vec<Stmt>, Vec<ParseError>> {
     let mut iter = tokens.iter().peekable();
-    parse_expression(&mut iter)
+    let mut statements = Vec::new();
+    let mut errors = Vec::new();
+    while iter.peek().is_some() {
+        match parse_declaration(&mut iter) {
+            Ok(statement) => statements.push(statement),
+            Err(error) => {
+                synchronize(&mut iter, &error);
+                errors.push(error);
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(statements)
+    } else {
+        Err(errors)
+    }
 }
 
-fn parse_expression<'a, I>(tokens: &mut Peekable<I>) -> Expr
+// Parses a single expression and requires it to consume every token, so
+// that inputs like `1 + 2)` or `1 2` are rejected instead of silently
+// parsing only a leading prefix of the input.
+pub fn parse_complete(tokens: Vec<TokenWithContext>) -> Result<Expr, ParseError> {
+    let mut iter = tokens.iter().peekable();
+    let expr = parse_expression(&mut iter)?;
+    match iter.next() {
+        Some(token) => Err(ParseError::TrailingTokens(token.clone())),
+        None => Ok(expr),
+    }
+}
+
+fn parse_declaration<'a, I>(tokens: &mut Peekable<I>) -> Result<Stmt, ParseError>
     where I: Iterator<Item = &'a TokenWithContext>
 {
-    parse_equality(tokens)
+    let is_var = match tokens.peek() {
+        Some(token) => token.token == Token::Var,
+        None => false,
+    };
+    if is_var {
+        let _ = tokens.next();
+        parse_var_declaration(tokens)
+    } else {
+        parse_statement(tokens)
+    }
 }
 
-fn parse_binary<'a, I>(tokens: &mut Peekable<I>,
-                       map_operator: &Fn(&Token) -> Option<Operator>,
-                       parse_subexpression: &Fn(&mut Peekable<I>) -> Expr)
-                       -> Expr
+fn parse_var_declaration<'a, I>(tokens: &mut Peekable<I>) -> Result<Stmt, ParseError>
     where I: Iterator<Item = &'a TokenWithContext>
 {
-    let mut expr;
-    {
-        expr = parse_subexpression(tokens);
+    let name = match tokens.next() {
+        Some(token) => {
+            match token.token {
+                Token::Identifier(ref name) => name.clone(),
+                _ => {
+                    return Err(ParseError::UnexpectedToken(token.clone(),
+                                                            "Expected variable name.".into()))
+                }
+            }
+        }
+        None => return Err(ParseError::UnexpectedEof),
     };
-    let peeked_token;
-    {
-        peeked_token = tokens.peek().cloned(); // Can I avoid this?
+    let has_initializer = match tokens.peek() {
+        Some(token) => token.token == Token::Equal,
+        None => false,
     };
-    while let Some(peeked_token) = peeked_token {
-        if let Some(mapped_operator) = map_operator(&peeked_token.token) {
-            {
-                // Just advance, we know all we need from the peeked value
-                let _ = tokens.next();
-            }
-            let right;
-            {
-                right = parse_subexpression(tokens);
-            };
-            let binary_expression = BinaryExpr {
-                left: expr,
-                operator: mapped_operator,
-                right: right,
-            };
-            expr = Expr::Binary(Box::new(binary_expression));
-        } else {
+    let initializer = if has_initializer {
+        let _ = tokens.next();
+        Some(parse_expression(tokens)?)
+    } else {
+        None
+    };
+    expect(tokens, &Token::Semicolon, "Expected ';' after variable declaration.")?;
+    Ok(Stmt::Var(name, initializer))
+}
+
+fn parse_statement<'a, I>(tokens: &mut Peekable<I>) -> Result<Stmt, ParseError>
+    where I: Iterator<Item = &'a TokenWithContext>
+{
+    let next_token = tokens.peek().map(|token| token.token.clone());
+    match next_token {
+        Some(Token::Print) => {
+            let _ = tokens.next();
+            let expr = parse_expression(tokens)?;
+            expect(tokens, &Token::Semicolon, "Expected ';' after value.")?;
+            Ok(Stmt::Print(expr))
+        }
+        Some(Token::LeftBrace) => {
+            let _ = tokens.next();
+            Ok(Stmt::Block(parse_block(tokens)?))
+        }
+        Some(Token::If) => {
+            let _ = tokens.next();
+            parse_if(tokens)
+        }
+        Some(Token::While) => {
+            let _ = tokens.next();
+            parse_while(tokens)
+        }
+        _ => {
+            let expr = parse_expression(tokens)?;
+            expect(tokens, &Token::Semicolon, "Expected ';' after expression.")?;
+            Ok(Stmt::Expr(expr))
+        }
+    }
+}
+
+fn parse_block<'a, I>(tokens: &mut Peekable<I>) -> Result<Vec<Stmt>, ParseError>
+    where I: Iterator<Item = &'a TokenWithContext>
+{
+    let mut statements = Vec::new();
+    loop {
+        let at_end = match tokens.peek() {
+            Some(token) => token.token == Token::RightBrace,
+            None => return Err(ParseError::UnexpectedEof),
+        };
+        if at_end {
             break;
         }
+        statements.push(parse_declaration(tokens)?);
     }
-    expr
+    let _ = tokens.next(); // consume the RightBrace
+    Ok(statements)
 }
 
-fn parse_equality<'a, I>(tokens: &mut Peekable<I>) -> Expr
+fn parse_if<'a, I>(tokens: &mut Peekable<I>) -> Result<Stmt, ParseError>
     where I: Iterator<Item = &'a TokenWithContext>
 {
-    fn map_operator(token: &Token) -> Option<Operator> {
-        match token {
-            &Token::BangEqual => Some(Operator::NotEqual),
-            &Token::EqualEqual => Some(Operator::Equal),
-            _ => None,
+    expect(tokens, &Token::LeftParen, "Expected '(' after 'if'.")?;
+    let condition = parse_expression(tokens)?;
+    expect(tokens, &Token::RightParen, "Expected ')' after if condition.")?;
+    let then_branch = Box::new(parse_statement(tokens)?);
+    let has_else = match tokens.peek() {
+        Some(token) => token.token == Token::Else,
+        None => false,
+    };
+    let else_branch = if has_else {
+        let _ = tokens.next();
+        Some(Box::new(parse_statement(tokens)?))
+    } else {
+        None
+    };
+    Ok(Stmt::If(condition, then_branch, else_branch))
+}
+
+fn parse_while<'a, I>(tokens: &mut Peekable<I>) -> Result<Stmt, ParseError>
+    where I: Iterator<Item = &'a TokenWithContext>
+{
+    expect(tokens, &Token::LeftParen, "Expected '(' after 'while'.")?;
+    let condition = parse_expression(tokens)?;
+    expect(tokens, &Token::RightParen, "Expected ')' after while condition.")?;
+    let body = Box::new(parse_statement(tokens)?);
+    Ok(Stmt::While(condition, body))
+}
+
+fn expect<'a, I>(tokens: &mut Peekable<I>,
+                  expected: &Token,
+                  message: &str)
+                  -> Result<(), ParseError>
+    where I: Iterator<Item = &'a TokenWithContext>
+{
+    match tokens.next() {
+        Some(token) => {
+            if &token.token == expected {
+                Ok(())
+            } else {
+                Err(ParseError::UnexpectedToken(token.clone(), message.into()))
+            }
         }
+        None => Err(ParseError::UnexpectedEof),
     }
-    parse_binary(tokens, &map_operator, &parse_comparison)
 }
 
-fn parse_comparison<'a, I>(tokens: &mut Peekable<I>) -> Expr
+// Advances past the offending token(s) until a likely statement boundary is
+// reached, so a later caller can keep parsing the rest of the input instead
+// of aborting on the first error. The offending token has already been
+// consumed by the time this runs; if it was a `;`, nothing more is skipped.
+fn synchronize<'a, I>(tokens: &mut Peekable<I>, error: &ParseError)
     where I: Iterator<Item = &'a TokenWithContext>
 {
-    fn map_operator(token: &Token) -> Option<Operator> {
-        match token {
-            &Token::Greater => Some(Operator::Greater),
-            &Token::GreaterEqual => Some(Operator::GreaterEqual),
-            &Token::Less => Some(Operator::Less),
-            &Token::LessEqual => Some(Operator::LessEqual),
-            _ => None,
+    if let Some(token) = error.offending_token() {
+        if token.token == Token::Semicolon {
+            return;
+        }
+    }
+    loop {
+        let starts_statement = match tokens.peek() {
+            Some(next) => {
+                match next.token {
+                    Token::Class | Token::Fun | Token::Var | Token::For | Token::If |
+                    Token::While | Token::Print | Token::Return => true,
+                    _ => false,
+                }
+            }
+            None => return,
+        };
+        if starts_statement {
+            return;
+        }
+        if let Some(token) = tokens.next() {
+            if token.token == Token::Semicolon {
+                return;
+            }
         }
     }
-    parse_binary(tokens, &map_operator, &parse_term)
 }
 
-fn parse_term<'a, I>(tokens: &mut Peekable<I>) -> Expr
+fn parse_expression<'a, I>(tokens: &mut Peekable<I>) -> Result<Expr, ParseError>
     where I: Iterator<Item = &'a TokenWithContext>
 {
-    fn map_operator(token: &Token) -> Option<Operator> {
-        match token {
-            &Token::Minus => Some(Operator::Minus),
-            &Token::Plus => Some(Operator::Plus),
-            _ => None,
+    parse_assignment(tokens)
+}
+
+// Assignment binds the loosest of all and is right-associative: `a = b = c`
+// parses as `a = (b = c)`. It sits above the binding-power table below
+// because its left-hand side needs validating as an assignment target
+// rather than being folded into a `BinaryExpr`.
+fn parse_assignment<'a, I>(tokens: &mut Peekable<I>) -> Result<Expr, ParseError>
+    where I: Iterator<Item = &'a TokenWithContext>
+{
+    let expr = parse_binary_expr(tokens, 0)?;
+    let has_equals = match tokens.peek() {
+        Some(token) => token.token == Token::Equal,
+        None => false,
+    };
+    if !has_equals {
+        return Ok(expr);
+    }
+    let equals_token = tokens.next().unwrap().clone();
+    let value = parse_assignment(tokens)?;
+    match expr {
+        Expr::Variable(name) => Ok(Expr::Assign(Box::new(Assign {
+            name: name,
+            value: value,
+        }))),
+        _ => {
+            Err(ParseError::UnexpectedToken(equals_token, "Invalid assignment target.".into()))
         }
     }
-    parse_binary(tokens, &map_operator, &parse_factor)
 }
 
-fn parse_factor<'a, I>(tokens: &mut Peekable<I>) -> Expr
+// Binding powers for each infix operator, lowest precedence first. A
+// left-associative operator gets `(n, n + 1)` so that, at equal precedence,
+// the loop in `parse_binary_expr` keeps folding to the left; a
+// right-associative one would get `(n + 1, n)` instead. `or` binds loosest,
+// then `and`, then the existing equality/comparison/term/factor levels.
+fn binding_power(token: &Token) -> Option<(u8, u8)> {
+    match token {
+        &Token::Or => Some((1, 2)),
+        &Token::And => Some((3, 4)),
+        &Token::BangEqual | &Token::EqualEqual => Some((5, 6)),
+        &Token::Greater | &Token::GreaterEqual | &Token::Less | &Token::LessEqual => Some((7, 8)),
+        &Token::Plus | &Token::Minus => Some((9, 10)),
+        &Token::Slash | &Token::Star => Some((11, 12)),
+        _ => None,
+    }
+}
+
+fn binary_operator(token: &Token) -> Operator {
+    match token {
+        &Token::Or => Operator::Or,
+        &Token::And => Operator::And,
+        &Token::BangEqual => Operator::NotEqual,
+        &Token::EqualEqual => Operator::Equal,
+        &Token::Greater => Operator::Greater,
+        &Token::GreaterEqual => Operator::GreaterEqual,
+        &Token::Less => Operator::Less,
+        &Token::LessEqual => Operator::LessEqual,
+        &Token::Plus => Operator::Plus,
+        &Token::Minus => Operator::Minus,
+        &Token::Slash => Operator::Slash,
+        &Token::Star => Operator::Star,
+        _ => unreachable!("binding_power and binary_operator must agree on operator tokens"),
+    }
+}
+
+// Precedence-climbing parser for the binary operators: parse a prefix/unary
+// "nud" via `parse_unary`, then keep folding infix operators whose left
+// binding power beats `min_bp` into `BinaryExpr`s, recursing into the right
+// operand with that operator's right binding power. Adding a new infix
+// operator is then a single `binding_power`/`binary_operator` table entry
+// instead of a whole new precedence function.
+fn parse_binary_expr<'a, I>(tokens: &mut Peekable<I>, min_bp: u8) -> Result<Expr, ParseError>
     where I: Iterator<Item = &'a TokenWithContext>
 {
-    fn map_operator(token: &Token) -> Option<Operator> {
-        match token {
-            &Token::Slash => Some(Operator::Slash),
-            &Token::Star => Some(Operator::Star),
-            _ => None,
+    let mut expr = parse_unary(tokens)?;
+    loop {
+        let operator_token = match tokens.peek() {
+            Some(peeked_token) => peeked_token.token.clone(),
+            None => break,
+        };
+        let (left_bp, right_bp) = match binding_power(&operator_token) {
+            Some(binding_powers) => binding_powers,
+            None => break,
+        };
+        if left_bp <= min_bp {
+            break;
         }
+        let _ = tokens.next();
+        let right = parse_binary_expr(tokens, right_bp)?;
+        let binary_expression = BinaryExpr {
+            left: expr,
+            operator: binary_operator(&operator_token),
+            right: right,
+        };
+        expr = Expr::Binary(Box::new(binary_expression));
     }
-    parse_binary(tokens, &map_operator, &parse_unary)
+    Ok(expr)
 }
 
-fn parse_unary<'a, I>(tokens: &mut Peekable<I>) -> Expr
+fn parse_unary<'a, I>(tokens: &mut Peekable<I>) -> Result<Expr, ParseError>
     where I: Iterator<Item = &'a TokenWithContext>
 {
     fn map_operator(token: &Token) -> Option<Operator> {
@@ -127,19 +384,19 @@ fn parse_unary<'a, I>(tokens: &mut Peekable<I>) -> Expr
         }
         let right;
         {
-            right = parse_unary(tokens);
+            right = parse_unary(tokens)?;
         };
         let unary_expression = UnaryExpr {
             operator: mapped_operator,
             right: right,
         };
-        return Expr::Unary(Box::new(unary_expression));
+        return Ok(Expr::Unary(Box::new(unary_expression)));
     } else {
         parse_primary(tokens)
     }
 }
 
-fn parse_primary<'a, I>(tokens: &mut Peekable<I>) -> Expr
+fn parse_primary<'a, I>(tokens: &mut Peekable<I>) -> Result<Expr, ParseError>
     where I: Iterator<Item = &'a TokenWithContext>
 {
     let primary_token;
@@ -148,30 +405,32 @@ fn parse_primary<'a, I>(tokens: &mut Peekable<I>) -> Expr
     };
     if let Some(primary_token) = primary_token {
         match primary_token.token {
-            Token::False => Expr::Literal(Literal::BoolLiteral(false)),
-            Token::True => Expr::Literal(Literal::BoolLiteral(true)),
-            Token::Nil => Expr::Literal(Literal::NilLiteral),
-            Token::NumberLiteral(n) => Expr::Literal(Literal::NumberLiteral(n)),
-            Token::StringLiteral(ref s) => Expr::Literal(Literal::StringLiteral(s.clone())),
+            Token::False => Ok(Expr::Literal(Literal::BoolLiteral(false))),
+            Token::True => Ok(Expr::Literal(Literal::BoolLiteral(true))),
+            Token::Nil => Ok(Expr::Literal(Literal::NilLiteral)),
+            Token::NumberLiteral(n) => Ok(Expr::Literal(Literal::NumberLiteral(n))),
+            Token::StringLiteral(ref s) => Ok(Expr::Literal(Literal::StringLiteral(s.clone()))),
+            Token::Identifier(ref name) => Ok(Expr::Variable(name.clone())),
             Token::LeftParen => {
                 let expr;
                 {
-                    expr = parse_expression(tokens);
+                    expr = parse_expression(tokens)?;
                 };
                 {
                     if let Some(token) = tokens.next() {
-                        if token.token == Token::LeftParen {
+                        if token.token == Token::RightParen {
                             let grouping_expression = Grouping { expr: expr };
-                            return Expr::Grouping(Box::new(grouping_expression));
+                            return Ok(Expr::Grouping(Box::new(grouping_expression)));
                         }
+                        return Err(ParseError::MissingRightParen(token.clone()));
                     }
-                    unimplemented!()
+                    Err(ParseError::UnexpectedEof)
                 }
             }
-            _ => unimplemented!(),
+            _ => Err(ParseError::ExpectedExpression(primary_token.clone())),
         }
     } else {
-        unimplemented!()
+        Err(ParseError::UnexpectedEof)
     }
 }
 
@@ -181,17 +440,135 @@ mod tests {
     use parser::*;
     use pretty_printer::PrettyPrint;
 
+    fn parse_single_expr_stmt(source: &str) -> Expr {
+        let tokens = scan(&source.into()).unwrap();
+        let mut statements = parse(tokens).unwrap();
+        assert_eq!(1, statements.len());
+        match statements.remove(0) {
+            Stmt::Expr(expr) => expr,
+            other => panic!("expected an expression statement, got {:?}", other),
+        }
+    }
+
     #[test]
     fn literal() {
-        let tokens = scan(&"123".into()).unwrap();
-        let expr = parse(tokens);
+        let expr = parse_single_expr_stmt("123;");
         assert_eq!("123", &expr.pretty_print());
     }
 
     #[test]
     fn binary() {
-        let tokens = scan(&"123+456".into()).unwrap();
-        let expr = parse(tokens);
+        let expr = parse_single_expr_stmt("123+456;");
         assert_eq!("123 + 456", &expr.pretty_print());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn factor_binds_tighter_than_term() {
+        let expr = parse_single_expr_stmt("1+2*3;");
+        assert_eq!("1 + 2 * 3", &expr.pretty_print());
+        match expr {
+            Expr::Binary(ref binary) => assert_eq!(Operator::Plus, binary.operator),
+            ref other => panic!("expected a top-level `+`, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expr = parse_single_expr_stmt("a and b or c;");
+        match expr {
+            Expr::Binary(ref binary) => assert_eq!(Operator::Or, binary.operator),
+            ref other => panic!("expected a top-level `or`, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn assignment_to_variable() {
+        let expr = parse_single_expr_stmt("x = 1;");
+        match expr {
+            Expr::Assign(ref assign) => {
+                assert_eq!("x", &assign.name);
+                assert_eq!("1", &assign.value.pretty_print());
+            }
+            ref other => panic!("expected an assignment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn assignment_to_non_variable_is_rejected() {
+        let tokens = scan(&"1 + 2 = 3;".into()).unwrap();
+        assert!(parse(tokens).is_err());
+    }
+
+    #[test]
+    fn missing_right_paren_is_reported() {
+        let tokens = scan(&"(123+456;".into()).unwrap();
+        let errors = parse(tokens).unwrap_err();
+        assert_eq!(1, errors.len());
+    }
+
+    #[test]
+    fn grouping_requires_matching_right_paren() {
+        let expr = parse_single_expr_stmt("(1 + 2);");
+        assert_eq!("(1 + 2)", &expr.pretty_print());
+    }
+
+    #[test]
+    fn parse_complete_accepts_a_single_expression() {
+        let tokens = scan(&"1 + 2".into()).unwrap();
+        let expr = parse_complete(tokens).unwrap();
+        assert_eq!("1 + 2", &expr.pretty_print());
+    }
+
+    #[test]
+    fn parse_complete_rejects_trailing_tokens() {
+        let tokens = scan(&"1 2".into()).unwrap();
+        assert!(parse_complete(tokens).is_err());
+    }
+
+    #[test]
+    fn parse_complete_rejects_unmatched_right_paren() {
+        let tokens = scan(&"1 + 2)".into()).unwrap();
+        assert!(parse_complete(tokens).is_err());
+    }
+
+    #[test]
+    fn error_message_includes_source_position() {
+        let tokens = scan(&"1 + ;".into()).unwrap();
+        let errors = parse(tokens).unwrap_err();
+        let message = format!("{}", errors[0]);
+        assert!(message.starts_with("[line 1] Error at"));
+    }
+
+    #[test]
+    fn var_declaration_with_initializer() {
+        let tokens = scan(&"var x = 1;".into()).unwrap();
+        let statements = parse(tokens).unwrap();
+        match statements[0] {
+            Stmt::Var(ref name, Some(ref initializer)) => {
+                assert_eq!("x", name);
+                assert_eq!("1", &initializer.pretty_print());
+            }
+            ref other => panic!("expected a var declaration, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn block_collects_statements() {
+        let tokens = scan(&"{ var x = 1; print x; }".into()).unwrap();
+        let statements = parse(tokens).unwrap();
+        match statements[0] {
+            Stmt::Block(ref inner) => assert_eq!(2, inner.len()),
+            ref other => panic!("expected a block, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn if_without_else() {
+        let tokens = scan(&"if (true) print 1;".into()).unwrap();
+        let statements = parse(tokens).unwrap();
+        match statements[0] {
+            Stmt::If(_, _, None) => {}
+            ref other => panic!("expected an if with no else branch, got {:?}", other),
+        }
+    }
+}